@@ -1,16 +1,355 @@
 use anyhow::{bail, Result};
 use log::{debug, error, info};
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::process::{Command, Stdio};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{
+    Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio,
+};
+use std::sync::mpsc;
+use std::time::Duration;
 
 mod os;
 mod unix;
 
+pub mod smf;
+
 static SVCCFG_BIN: &str = "/usr/sbin/svccfg";
 static SVCPROP_BIN: &str = "/usr/bin/svcprop";
 static DEVPROP_BIN: &str = "/sbin/devprop";
 
+/*
+ * When a timeout fires we give the child this long to exit after SIGTERM
+ * before we escalate to SIGKILL.
+ */
+static KILL_GRACE: Duration = Duration::from_secs(5);
+
+/**
+ * Returned when a bounded `run*` call does not complete within its deadline.
+ * Distinct from the generic failure `bail!()`s so that callers can match on a
+ * timeout (via `anyhow::Error::downcast_ref`) and react differently from, say,
+ * a non-zero exit.
+ */
+#[derive(Debug)]
+pub struct Timeout;
+
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "command did not complete before the timeout elapsed")
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/*
+ * Wait for a child, optionally bounding how long we are willing to block.
+ *
+ * With no timeout we just wait inline.  With a timeout we hand the child to a
+ * monitor thread that does the blocking wait() and posts the ExitStatus back
+ * down a channel; we recv_timeout() on that channel.  If the deadline passes we
+ * SIGTERM the child pid, wait a short grace period, then SIGKILL, always
+ * draining the monitor afterwards so the reaped status is collected and no
+ * zombie is left behind.  Note we signal only the child itself, not its
+ * process group, so any grandchildren it forked (e.g. via svccfg or zlogin)
+ * are not reaped by the timeout.
+ */
+fn wait_or_kill(
+    mut child: Child,
+    timeout: Option<Duration>,
+) -> Result<ExitStatus> {
+    let dur = match timeout {
+        None => return Ok(child.wait()?),
+        Some(dur) => dur,
+    };
+
+    let pid = child.id() as libc::pid_t;
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(child.wait());
+    });
+
+    match rx.recv_timeout(dur) {
+        Ok(res) => Ok(res?),
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            unsafe { libc::kill(pid, libc::SIGTERM) };
+            if rx.recv_timeout(KILL_GRACE).is_err() {
+                unsafe { libc::kill(pid, libc::SIGKILL) };
+                let _ = rx.recv();
+            }
+            Err(Timeout.into())
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            bail!("monitor thread for pid {} exited without a status", pid)
+        }
+    }
+}
+
+/*
+ * illumos privilege-set type names, as understood by priv_str_to_set(3C) and
+ * accepted as the `which` argument to setppriv(2).  priv_ptype_t is just a C
+ * string on illumos.
+ */
+static PRIV_EFFECTIVE: &[u8] = b"Effective\0";
+static PRIV_PERMITTED: &[u8] = b"Permitted\0";
+static PRIV_INHERITABLE: &[u8] = b"Inheritable\0";
+
+/*
+ * priv.h(3HEAD) declares priv_set_t as an opaque type; callers only ever hold
+ * pointers to one returned by priv_str_to_set(3C).  The `libc` crate does not
+ * expose any of these symbols, so declare the small slice of priv.h we need
+ * ourselves rather than pretending `libc::` has them.
+ */
+#[repr(C)]
+struct priv_set_t {
+    _opaque: [u8; 0],
+}
+
+/*
+ * priv_op_t from <priv.h>; the only operation we use is PRIV_SET, which
+ * replaces a set wholesale rather than adding or removing from it.
+ */
+const PRIV_OP_SET: libc::c_int = 2;
+
+extern "C" {
+    fn priv_str_to_set(
+        buf: *const libc::c_char,
+        sep: *const libc::c_char,
+        endptr: *mut *const libc::c_char,
+    ) -> *mut priv_set_t;
+    fn priv_freeset(sp: *mut priv_set_t);
+    fn setppriv(
+        op: libc::c_int,
+        which: *const libc::c_char,
+        set: *const priv_set_t,
+    ) -> libc::c_int;
+}
+
+/**
+ * A reduced identity and privilege set to apply to a child before it execs.
+ *
+ * This is important when a privileged SMF service shells out to untrusted
+ * tools: supply a target `uid`/`gid`, any supplementary `groups`, and an
+ * illumos privilege specification (e.g. `"basic,!proc_exec"`) and the child
+ * runs with only those rights.
+ *
+ * Note that whenever a `uid` or `gid` is set we call `setgroups()` with
+ * exactly the `groups` list (empty clears all supplementary groups), so the
+ * child never silently inherits root's group memberships.  Callers that want
+ * to keep a supplementary group must list it explicitly.  The `privileges`
+ * spec is left untouched when `None`.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Privileges {
+    pub uid: Option<libc::uid_t>,
+    pub gid: Option<libc::gid_t>,
+    pub groups: Vec<libc::gid_t>,
+    pub privileges: Option<String>,
+}
+
+/*
+ * An owned priv_set_t allocated by priv_str_to_set(3C).  We build it in the
+ * parent (priv_str_to_set calls malloc, which is not legal post-fork) and move
+ * it into the pre_exec closure; it is freed when the owning Command is dropped
+ * in the parent.  The child's forked copy of the pointer is never freed — the
+ * exec replaces the address space.
+ */
+struct PrivSet(*mut priv_set_t);
+
+unsafe impl Send for PrivSet {}
+unsafe impl Sync for PrivSet {}
+
+impl Drop for PrivSet {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { priv_freeset(self.0) };
+        }
+    }
+}
+
+/*
+ * The result of resolving a `Privileges` in the parent into a form whose
+ * application in the child requires no heap allocation.
+ */
+struct PreparedPrivs {
+    uid: Option<libc::uid_t>,
+    gid: Option<libc::gid_t>,
+    groups: Option<Vec<libc::gid_t>>,
+    privset: Option<PrivSet>,
+}
+
+/*
+ * Do the allocating work — CString construction and priv_str_to_set(3C) — in
+ * the parent, so the pre_exec closure is left with only async-signal-safe
+ * syscalls.
+ */
+fn prepare_privileges(p: &Privileges) -> Result<PreparedPrivs> {
+    let privset = match &p.privileges {
+        None => None,
+        Some(spec) => {
+            let spec = std::ffi::CString::new(spec.as_str())?;
+            let set = unsafe {
+                priv_str_to_set(
+                    spec.as_ptr(),
+                    b" ,\0".as_ptr() as *const libc::c_char,
+                    std::ptr::null_mut(),
+                )
+            };
+            if set.is_null() {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Some(PrivSet(set))
+        }
+    };
+
+    /*
+     * Clear supplementary groups (setgroups with the given, possibly empty,
+     * list) whenever we are changing identity at all, so root's groups are not
+     * left attached by default.
+     */
+    let groups = if p.uid.is_some() || p.gid.is_some() || !p.groups.is_empty() {
+        Some(p.groups.clone())
+    } else {
+        None
+    };
+
+    Ok(PreparedPrivs { uid: p.uid, gid: p.gid, groups, privset })
+}
+
+/*
+ * Run inside the post-fork, pre-exec window of the child process, using only
+ * async-signal-safe syscalls on data prepared in the parent.  We drop the
+ * supplementary groups first, then the gid, then the uid last (setuid() would
+ * otherwise strip the privilege needed to set the others), and finally clamp
+ * the privilege sets.  Any failure returns an io::Error so the spawn fails
+ * rather than leaving the child running with elevated rights.
+ */
+fn apply_privileges(p: &PreparedPrivs) -> std::io::Result<()> {
+    unsafe {
+        if let Some(groups) = &p.groups {
+            if libc::setgroups(groups.len() as libc::c_int, groups.as_ptr())
+                != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(gid) = p.gid {
+            if libc::setgid(gid) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(uid) = p.uid {
+            if libc::setuid(uid) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        if let Some(set) = &p.privset {
+            for which in [PRIV_PERMITTED, PRIV_EFFECTIVE, PRIV_INHERITABLE] {
+                if setppriv(
+                    PRIV_OP_SET,
+                    which.as_ptr() as *const libc::c_char,
+                    set.0,
+                ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * The zone in which a command should execute.  `Global` runs in the global
+ * zone (the default); `Named` names a non-global zone whose id is resolved at
+ * spawn time.
+ *
+ * When the caller lacks `PRIV_PROC_ZONE`, entering a `Named` zone falls back
+ * to wrapping the command with `zlogin(1)` rather than `zone_enter(2)` (see
+ * `ZoneExec::Zlogin`).  That fallback does not propagate `env` into the
+ * zone — zlogin starts a fresh session inside the target zone rather than
+ * inheriting the wrapper process's environment — so callers combining `Zone`
+ * with `env` (e.g. `svccfg`'s `alt_root` variables) should not assume those
+ * variables are visible unless the caller holds `PRIV_PROC_ZONE`.  Combining
+ * `Zone` with `Privileges` is rejected outright in that fallback; see
+ * `build_cmd`.
+ */
+#[derive(Debug, Clone)]
+pub enum Zone {
+    Global,
+    Named(String),
+}
+
+static ZLOGIN_BIN: &str = "/usr/sbin/zlogin";
+
+/*
+ * The `libc` crate carries the `zoneid_t` alias but none of the zones(3LIB)
+ * functions themselves, so declare the two we need ourselves, along with
+ * priv_ineffect(3C) from <priv.h>.
+ */
+extern "C" {
+    fn getzoneidbyname(name: *const libc::c_char) -> libc::zoneid_t;
+    fn zone_enter(zoneid: libc::zoneid_t) -> libc::c_int;
+    fn priv_ineffect(privilege: *const libc::c_char) -> libc::c_int;
+}
+
+/*
+ * The privilege zone_enter(2) requires of the calling process; checked with
+ * priv_ineffect(3C) before we commit to the zone_enter pre_exec path.
+ */
+static PRIV_PROC_ZONE: &[u8] = b"proc_zone\0";
+
+/*
+ * How a command targeting a non-global zone will actually get there.
+ */
+enum ZoneExec {
+    /*
+     * Enter the zone from a pre_exec closure via zone_enter(2).  Requires
+     * PRIV_PROC_ZONE in our effective set.
+     */
+    Enter(libc::zoneid_t),
+    /*
+     * We lack PRIV_PROC_ZONE, so front the command with zlogin(1) instead,
+     * which is setuid and can enter the zone on our behalf.
+     */
+    Zlogin(String),
+}
+
+/*
+ * Decide how to reach a zone and resolve whatever that requires in the
+ * parent.  getzoneidbyname(3C) builds a CString and may allocate, so it must
+ * not run in the post-fork pre_exec window; the global zone needs no entering
+ * and yields None.
+ */
+fn prepare_zone(zone: &Zone) -> Result<Option<ZoneExec>> {
+    let name = match zone {
+        Zone::Global => return Ok(None),
+        Zone::Named(name) => name,
+    };
+
+    let have_proc_zone = unsafe {
+        priv_ineffect(PRIV_PROC_ZONE.as_ptr() as *const libc::c_char) != 0
+    };
+
+    if !have_proc_zone {
+        return Ok(Some(ZoneExec::Zlogin(name.clone())));
+    }
+
+    let cname = std::ffi::CString::new(name.as_str())?;
+
+    let zoneid = unsafe { getzoneidbyname(cname.as_ptr()) };
+    if zoneid < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(Some(ZoneExec::Enter(zoneid)))
+}
+
 fn spawn_reader<T>(
     name: &str,
     stream: Option<T>,
@@ -55,7 +394,13 @@ where
 
 pub fn devprop<S: AsRef<str>>(key: S) -> Result<String> {
     let key = key.as_ref();
-    let val = run_capture_stdout(vec![DEVPROP_BIN, key].as_ref(), None)?;
+    let val = run_capture_stdout(
+        vec![DEVPROP_BIN, key].as_ref(),
+        None,
+        None,
+        None,
+        None,
+    )?;
     let lines: Vec<_> = val.lines().collect();
     if lines.len() != 1 {
         bail!("unexpected output for devprop {}: {:?}", key, lines);
@@ -90,13 +435,16 @@ pub fn svccfg<S: AsRef<str>>(args: &[S], alt_root: Option<S>) -> Result<()> {
         stdin += &format!("{}\n", arg)
     }
 
-    run_with_stdin(&svccfg, env, stdin)
+    run_with_stdin(&svccfg, env, stdin, None, None, None)
 }
 
 pub fn svcprop(fmri: &str, prop_val: &str) -> Result<String> {
     let val = run_capture_stdout(
         vec![SVCPROP_BIN, "-p", prop_val, fmri].as_ref(),
         None,
+        None,
+        None,
+        None,
     )?;
     let lines: Vec<_> = val.lines().collect();
     if lines.len() != 1 {
@@ -109,10 +457,13 @@ pub fn run_with_stdin<S: AsRef<str>>(
     args: &[S],
     env: Option<&[(S, S)]>,
     stdin: String,
+    timeout: Option<Duration>,
+    privs: Option<&Privileges>,
+    zone: Option<&Zone>,
 ) -> Result<()> {
     let args: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
     let env = build_env(env);
-    let mut cmd = build_cmd(args, env);
+    let mut cmd = build_cmd(&args, env, privs, zone)?;
 
     cmd.stdin(Stdio::piped());
     cmd.stdout(Stdio::piped());
@@ -121,12 +472,19 @@ pub fn run_with_stdin<S: AsRef<str>>(
     let mut child = cmd.spawn()?;
     let mut child_stdin = child.stdin.take().unwrap();
     std::thread::spawn(move || {
-        child_stdin.write_all(stdin.as_bytes()).unwrap();
+        /*
+         * Ignore write errors: if we time out and tear the child down, the
+         * pipe is closed under us and this write fails with EPIPE.  Panicking
+         * here (as an .unwrap() would) could otherwise wedge the timeout path.
+         */
+        let _ = child_stdin.write_all(stdin.as_bytes());
     });
 
     let readout = spawn_reader("O", child.stdout.take());
     let readerr = spawn_reader("E", child.stderr.take());
 
+    let status = wait_or_kill(child, timeout);
+
     if let Some(t) = readout {
         t.join().expect("join stdout thread");
     }
@@ -134,22 +492,23 @@ pub fn run_with_stdin<S: AsRef<str>>(
         t.join().expect("join stderr thread");
     }
 
-    match child.wait() {
-        Err(e) => Err(e.into()),
-        Ok(es) => {
-            if !es.success() {
-                bail!("exec {:?}: failed {:?}", &args, &es)
-            } else {
-                Ok(())
-            }
-        }
+    let es = status?;
+    if !es.success() {
+        bail!("exec {:?}: failed {:?}", &args, &es)
     }
+    Ok(())
 }
 
-pub fn run<S: AsRef<str>>(args: &[S], env: Option<&[(S, S)]>) -> Result<()> {
+pub fn run<S: AsRef<str>>(
+    args: &[S],
+    env: Option<&[(S, S)]>,
+    timeout: Option<Duration>,
+    privs: Option<&Privileges>,
+    zone: Option<&Zone>,
+) -> Result<()> {
     let args: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
     let env = build_env(env);
-    let mut cmd = build_cmd(args, env);
+    let mut cmd = build_cmd(&args, env, privs, zone)?;
 
     cmd.stdin(Stdio::null());
     cmd.stdout(Stdio::piped());
@@ -160,6 +519,8 @@ pub fn run<S: AsRef<str>>(args: &[S], env: Option<&[(S, S)]>) -> Result<()> {
     let readout = spawn_reader("O", child.stdout.take());
     let readerr = spawn_reader("E", child.stderr.take());
 
+    let status = wait_or_kill(child, timeout);
+
     if let Some(t) = readout {
         t.join().expect("join stdout thread");
     }
@@ -167,39 +528,247 @@ pub fn run<S: AsRef<str>>(args: &[S], env: Option<&[(S, S)]>) -> Result<()> {
         t.join().expect("join stderr thread");
     }
 
-    match child.wait() {
-        Err(e) => Err(e.into()),
-        Ok(es) => {
-            if !es.success() {
-                bail!("exec {:?}: failed {:?}", &args, &es)
-            } else {
-                Ok(())
-            }
-        }
+    let es = status?;
+    if !es.success() {
+        bail!("exec {:?}: failed {:?}", &args, &es)
     }
+    Ok(())
 }
 
 pub fn run_capture_stdout<S: AsRef<str>>(
     args: &[S],
     env: Option<&[(S, S)]>,
+    timeout: Option<Duration>,
+    privs: Option<&Privileges>,
+    zone: Option<&Zone>,
 ) -> Result<String> {
     let args: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
     let env = build_env(env);
-    let mut cmd = build_cmd(args, env);
+    let mut cmd = build_cmd(&args, env, privs, zone)?;
 
     cmd.stdin(Stdio::null());
     cmd.stdout(Stdio::piped());
     cmd.stderr(Stdio::piped());
 
-    let output = cmd.output()?;
-    if output.status.success() {
-        Ok(String::from_utf8(output.stdout)?)
+    let mut child = cmd.spawn()?;
+
+    /*
+     * Drain both pipes on their own threads so a large stderr cannot wedge a
+     * full stdout pipe (or vice versa) while we wait.
+     */
+    let out = drain(child.stdout.take());
+    let err = drain(child.stderr.take());
+
+    let status = wait_or_kill(child, timeout);
+
+    let stdout = match out {
+        Some(t) => t.join().expect("join stdout thread")?,
+        None => String::new(),
+    };
+    let stderr = match err {
+        Some(t) => t.join().expect("join stderr thread")?,
+        None => String::new(),
+    };
+
+    let es = status?;
+    if es.success() {
+        Ok(stdout)
     } else {
-        bail!(
-            "exec {:?}: failed {:?}",
-            &args,
-            String::from_utf8(output.stderr)?
-        )
+        bail!("exec {:?}: failed {:?}", &args, stderr)
+    }
+}
+
+fn drain<T>(
+    stream: Option<T>,
+) -> Option<std::thread::JoinHandle<std::io::Result<String>>>
+where
+    T: Read + Send + 'static,
+{
+    stream.map(|mut stream| {
+        std::thread::spawn(move || {
+            let mut buf = String::new();
+            stream.read_to_string(&mut buf)?;
+            Ok(buf)
+        })
+    })
+}
+
+/**
+ * The captured result of a subprocess: its exit status plus the full contents
+ * of both output streams.  Unlike `run_capture_stdout`, stderr is preserved,
+ * and a non-zero exit still yields whatever was captured rather than an error.
+ */
+pub struct Output {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+pub fn run_capture<S: AsRef<str>>(
+    args: &[S],
+    env: Option<&[(S, S)]>,
+    timeout: Option<Duration>,
+    privs: Option<&Privileges>,
+    zone: Option<&Zone>,
+) -> Result<Output> {
+    let args: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
+    let env = build_env(env);
+    let mut cmd = build_cmd(&args, env, privs, zone)?;
+
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+
+    let mut child = cmd.spawn()?;
+
+    /*
+     * Drain both pipes concurrently so a full stderr pipe cannot wedge stdout
+     * (or vice versa).  Each line is teed to the log as the one-shot helpers
+     * do, while the full text is captured for the caller.
+     */
+    let readout = spawn_capture("O", child.stdout.take());
+    let readerr = spawn_capture("E", child.stderr.take());
+
+    let status = wait_or_kill(child, timeout);
+
+    let stdout = match readout {
+        Some(t) => t.join().expect("join stdout thread")?,
+        None => String::new(),
+    };
+    let stderr = match readerr {
+        Some(t) => t.join().expect("join stderr thread")?,
+        None => String::new(),
+    };
+
+    Ok(Output { status: status?, stdout, stderr })
+}
+
+/*
+ * Like spawn_reader, but also accumulates the stream into an owned buffer that
+ * is returned when the thread is joined.  Unlike spawn_reader, which exits the
+ * process on a read error, a read error (e.g. non-UTF8 bytes on the pipe) is
+ * logged and returned to the caller as the thread's io::Result, as drain()
+ * does; whatever was captured before the failure is discarded.
+ */
+fn spawn_capture<T>(
+    name: &str,
+    stream: Option<T>,
+) -> Option<std::thread::JoinHandle<std::io::Result<String>>>
+where
+    T: Read + Send + 'static,
+{
+    let name = name.to_string();
+    let stream = stream?;
+
+    Some(std::thread::spawn(move || {
+        let mut r = BufReader::new(stream);
+        let mut out = String::new();
+
+        loop {
+            let mut buf = String::new();
+
+            match r.read_line(&mut buf) {
+                Ok(0) => return Ok(out),
+                Ok(_) => {
+                    let s = buf.trim();
+                    if !s.is_empty() {
+                        info!(target: "illumos-rs", "{}| {}", name, s);
+                    }
+                    out.push_str(&buf);
+                }
+                Err(e) => {
+                    error!(target: "illumos-rs", "failed to read {}: {}", name, e);
+                    return Err(e);
+                }
+            }
+        }
+    }))
+}
+
+/**
+ * A long-lived helper process driven over a newline-delimited line protocol,
+ * modelled on JSON-RPC.  Unlike the one-shot `run*` helpers, a `Process` keeps
+ * the child alive and owns its buffered stdin/stdout so callers can exchange
+ * many request/response pairs without paying the fork/exec cost each time.
+ *
+ * stderr is still drained to the log by the usual reader thread, so a helper's
+ * diagnostics reach the log while the protocol runs on stdout.
+ */
+pub struct Process {
+    child: Child,
+    writer: BufWriter<ChildStdin>,
+    reader: BufReader<ChildStdout>,
+    readerr: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Process {
+    /**
+     * Spawn a helper and take ownership of its pipes.  `env` matches the
+     * `run*` helpers; stdin and stdout carry the protocol and stderr is logged.
+     */
+    pub fn start<S: AsRef<str>>(
+        args: &[S],
+        env: Option<&[(S, S)]>,
+    ) -> Result<Process> {
+        let args: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
+        let env = build_env(env);
+        let mut cmd = build_cmd(&args, env, None, None)?;
+
+        cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        let mut child = cmd.spawn()?;
+        let writer = BufWriter::new(child.stdin.take().unwrap());
+        let reader = BufReader::new(child.stdout.take().unwrap());
+        let readerr = spawn_reader("E", child.stderr.take());
+
+        Ok(Process { child, writer, reader, readerr })
+    }
+
+    /**
+     * Serialize `req` as a single JSON line, write it to the child, then read
+     * and deserialize exactly one response line from the child's stdout.
+     *
+     * Note that the read is unbounded: unlike the `run*` helpers there is no
+     * timeout here, so a wedged helper that never replies will block the caller
+     * indefinitely.  `Process` offers no way to interrupt a pending `send()`,
+     * and `close()` waits for the child without signalling it.
+     */
+    pub fn send<T, R>(&mut self, req: &T) -> Result<R>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        let line = serde_json::to_string(req)?;
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        let mut resp = String::new();
+        if self.reader.read_line(&mut resp)? == 0 {
+            bail!("helper closed stdout before responding");
+        }
+
+        Ok(serde_json::from_str(resp.trim_end())?)
+    }
+
+    /**
+     * Flush any buffered request, close the child's stdin so it sees EOF, drain
+     * the stderr reader, and wait for the child to exit.
+     */
+    pub fn close(self) -> Result<ExitStatus> {
+        let Process { mut child, mut writer, reader, readerr } = self;
+
+        writer.flush()?;
+        drop(writer);
+        drop(reader);
+
+        if let Some(t) = readerr {
+            t.join().expect("join stderr thread");
+        }
+
+        Ok(child.wait()?)
     }
 }
 
@@ -215,8 +784,49 @@ fn build_env<S: AsRef<str>>(
     }
 }
 
-fn build_cmd(args: Vec<&str>, env: Option<Vec<(&str, &str)>>) -> Command {
-    let mut cmd = Command::new(&args[0]);
+fn build_cmd(
+    args: &[&str],
+    env: Option<Vec<(&str, &str)>>,
+    privs: Option<&Privileges>,
+    zone: Option<&Zone>,
+) -> Result<Command> {
+    let zone_exec = zone.map(prepare_zone).transpose()?.flatten();
+
+    /*
+     * Privilege-dropping only composes with the direct zone_enter(2) path,
+     * where apply_privileges runs in the pre_exec closure *after* we are
+     * already inside the target zone.  It cannot compose with the zlogin(1)
+     * fallback: that pre_exec would run in the wrapper process just before it
+     * execs a setuid-root zlogin, stripping the very privilege zlogin needs
+     * to enter the zone on our behalf.  Reject the combination outright
+     * rather than silently failing to enter the zone.
+     */
+    if privs.is_some() && matches!(zone_exec, Some(ZoneExec::Zlogin(_))) {
+        bail!(
+            "cannot drop privileges while falling back to zlogin(1) for zone \
+             entry (missing PRIV_PROC_ZONE); privilege-dropping requires the \
+             direct zone_enter(2) path"
+        );
+    }
+
+    /*
+     * When we lack PRIV_PROC_ZONE we can't zone_enter(2) from pre_exec, so
+     * front the real argv with zlogin(1) instead; it is setuid and can enter
+     * the zone on our behalf.  Otherwise run the caller's argv unchanged and
+     * enter the zone from pre_exec below.
+     */
+    let zlogin_zone = match &zone_exec {
+        Some(ZoneExec::Zlogin(name)) => Some(name.clone()),
+        _ => None,
+    };
+    let mut full_args: Vec<&str> = Vec::new();
+    if let Some(name) = &zlogin_zone {
+        full_args.push(ZLOGIN_BIN);
+        full_args.push(name);
+    }
+    full_args.extend_from_slice(args);
+
+    let mut cmd = Command::new(&full_args[0]);
     cmd.env_remove("LANG");
     cmd.env_remove("LC_CTYPE");
     cmd.env_remove("LC_NUMERIC");
@@ -226,15 +836,39 @@ fn build_cmd(args: Vec<&str>, env: Option<Vec<(&str, &str)>>) -> Command {
     cmd.env_remove("LC_MESSAGES");
     cmd.env_remove("LC_ALL");
 
-    if args.len() > 1 {
-        cmd.args(&args[1..]);
+    if full_args.len() > 1 {
+        cmd.args(&full_args[1..]);
     }
 
     if let Some(env) = env {
+        debug!(target: "illumos-rs", "exec: {:?} env={:?}", &full_args, &env);
         cmd.envs(env);
-        debug!(target: "illumos-rs", "exec: {:?} env={:?}", &args, &env);
     } else {
-        debug!(target: "illumos-rs", "exec: {:?}", &args);
+        debug!(target: "illumos-rs", "exec: {:?}", &full_args);
+    }
+
+    /*
+     * Enter the zone before dropping privileges, so that any privilege
+     * restriction applies within the target zone.  The zone id is resolved in
+     * the parent; the closure only calls the async-signal-safe zone_enter(2).
+     */
+    if let Some(ZoneExec::Enter(zoneid)) = zone_exec {
+        unsafe {
+            cmd.pre_exec(move || {
+                if zone_enter(zoneid) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    if let Some(privs) = privs {
+        let prepared = prepare_privileges(privs)?;
+        unsafe {
+            cmd.pre_exec(move || apply_privileges(&prepared));
+        }
     }
-    cmd
+
+    Ok(cmd)
 }
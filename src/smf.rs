@@ -0,0 +1,234 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+use crate::{run_capture_stdout, SVCPROP_BIN};
+
+/**
+ * A single SMF property value, typed according to the `scf_type` reported by
+ * svcprop(1).  The string-like types (`astring`, `ustring`, `fmri`,
+ * `net_address`) and the numeric types (`count`, `integer`) may all carry
+ * more than one value, so they hold a `Vec`; only `boolean` is scalar.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Property {
+    Astring(Vec<String>),
+    Ustring(Vec<String>),
+    Boolean(bool),
+    Count(Vec<u64>),
+    Integer(Vec<i64>),
+    Fmri(Vec<String>),
+    NetAddress(Vec<String>),
+}
+
+impl Property {
+    /*
+     * Build a Property from the `type` and `value` columns of a svcprop line.
+     */
+    fn parse(scf_type: &str, value: &str) -> Result<Property> {
+        Ok(match scf_type {
+            "astring" => Property::Astring(parse_values(value)),
+            "ustring" => Property::Ustring(parse_values(value)),
+            "fmri" => Property::Fmri(parse_values(value)),
+            "net_address" | "net_address_v4" | "net_address_v6" => {
+                Property::NetAddress(parse_values(value))
+            }
+            "boolean" => Property::Boolean(match value.trim() {
+                "true" => true,
+                "false" => false,
+                other => bail!("unexpected boolean value {:?}", other),
+            }),
+            "count" => Property::Count(parse_numbers(value)?),
+            "integer" => Property::Integer(parse_numbers(value)?),
+            other => bail!("unhandled svcprop type {:?}", other),
+        })
+    }
+}
+
+/**
+ * Read an entire property group from an FMRI, returning a map from property
+ * name to its typed value.  This runs `svcprop -p <pg> <fmri>` and parses the
+ * `name type value` lines it prints, so callers no longer have to re-parse
+ * svcprop output by hand.
+ */
+pub fn svcprop_group(
+    fmri: &str,
+    pg: &str,
+) -> Result<HashMap<String, Property>> {
+    let out = run_capture_stdout(
+        vec![SVCPROP_BIN, "-p", pg, fmri].as_ref(),
+        None,
+        None,
+        None,
+        None,
+    )?;
+
+    parse_properties(&out, Some(pg))
+}
+
+/*
+ * Parse the `name type value` lines emitted by svcprop.  When a property group
+ * name is supplied we strip the leading `pg/` from each property name so the
+ * map is keyed on the bare property name.
+ */
+fn parse_properties(
+    output: &str,
+    pg: Option<&str>,
+) -> Result<HashMap<String, Property>> {
+    let mut props = HashMap::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut fields = line.splitn(3, ' ');
+        let name = match fields.next() {
+            Some(name) => name,
+            None => bail!("svcprop line without a name: {:?}", line),
+        };
+        let scf_type = match fields.next() {
+            Some(scf_type) => scf_type,
+            None => bail!("svcprop line without a type: {:?}", line),
+        };
+        let value = fields.next().unwrap_or("");
+
+        let key = match pg {
+            Some(pg) => {
+                name.strip_prefix(&format!("{}/", pg)).unwrap_or(name)
+            }
+            None => name,
+        };
+
+        props.insert(key.to_string(), Property::parse(scf_type, value)?);
+    }
+
+    Ok(props)
+}
+
+/*
+ * The numeric types (`count`, `integer`) may also be multi-valued in SMF;
+ * parse each whitespace-separated value so one legal multi-value property does
+ * not fail the whole group read.
+ */
+fn parse_numbers<T>(value: &str) -> Result<Vec<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value.split_whitespace().map(|v| Ok(v.parse::<T>()?)).collect()
+}
+
+/*
+ * svcprop separates multiple values with spaces and backslash-escapes any
+ * space or backslash that appears within a value.  Split on the unescaped
+ * spaces and unescape the remainder.
+ */
+fn parse_values(s: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut cur = String::new();
+    let mut chars = s.chars();
+    let mut in_value = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    cur.push(next);
+                    in_value = true;
+                }
+            }
+            ' ' => {
+                if in_value {
+                    values.push(std::mem::take(&mut cur));
+                    in_value = false;
+                }
+            }
+            c => {
+                cur.push(c);
+                in_value = true;
+            }
+        }
+    }
+
+    if in_value {
+        values.push(cur);
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values_single() {
+        assert_eq!(parse_values("hello"), vec!["hello"]);
+    }
+
+    #[test]
+    fn parse_values_multi() {
+        assert_eq!(
+            parse_values("alpha beta gamma"),
+            vec!["alpha", "beta", "gamma"]
+        );
+    }
+
+    #[test]
+    fn parse_values_escapes() {
+        assert_eq!(
+            parse_values(r"one\ two three\\four"),
+            vec!["one two", "three\\four"]
+        );
+    }
+
+    #[test]
+    fn parse_values_empty() {
+        assert_eq!(parse_values(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn parse_numbers_single() {
+        let v: Vec<u64> = parse_numbers("42").unwrap();
+        assert_eq!(v, vec![42]);
+    }
+
+    #[test]
+    fn parse_numbers_multi() {
+        let v: Vec<i64> = parse_numbers("-1 0 1").unwrap();
+        assert_eq!(v, vec![-1, 0, 1]);
+    }
+
+    #[test]
+    fn parse_numbers_rejects_garbage() {
+        let v: Result<Vec<u64>> = parse_numbers("not-a-number");
+        assert!(v.is_err());
+    }
+
+    #[test]
+    fn parse_properties_empty_group() {
+        let props = parse_properties("", Some("config")).unwrap();
+        assert!(props.is_empty());
+    }
+
+    #[test]
+    fn parse_properties_strips_pg_prefix() {
+        let props = parse_properties(
+            "config/enabled boolean true\nconfig/retries count 3 4",
+            Some("config"),
+        )
+        .unwrap();
+        assert_eq!(props.get("enabled"), Some(&Property::Boolean(true)));
+        assert_eq!(
+            props.get("retries"),
+            Some(&Property::Count(vec![3, 4]))
+        );
+    }
+
+    #[test]
+    fn parse_properties_rejects_bad_boolean() {
+        let err = parse_properties("config/flag boolean maybe", Some("config"))
+            .unwrap_err();
+        assert!(err.to_string().contains("maybe"));
+    }
+}